@@ -0,0 +1,25 @@
+use std::collections::HashMap;
+
+use super::serialization::conversion::Conversion;
+
+/// A tool invocable by the model via an XML-ish tag: declares its name, the
+/// attributes/payload shown to the model as usage examples, and (optionally)
+/// the [`Conversion`] each should be coerced to once a response is parsed back.
+pub trait Action: Send + Sync {
+    fn name(&self) -> &str;
+    /// Example `name="value"` attributes shown in the action's usage block.
+    fn attributes(&self) -> Option<HashMap<String, String>>;
+    fn example_payload(&self) -> Option<&str>;
+
+    /// The `Conversion` each named attribute's captured string should be
+    /// coerced to. Attributes with no entry here are left as raw strings.
+    fn attribute_types(&self) -> HashMap<String, Conversion> {
+        HashMap::new()
+    }
+
+    /// The `Conversion` the payload's captured string should be coerced to,
+    /// if any. Left as a raw string when `None`.
+    fn payload_type(&self) -> Option<Conversion> {
+        None
+    }
+}
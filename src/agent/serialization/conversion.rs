@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use super::Invocation;
+
+/// Declares how a raw string captured for an invocation's attribute or
+/// payload should be coerced before it reaches an `Action` implementation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conversion {
+    Bytes,
+    String,
+    Integer,
+    Float,
+    Boolean,
+    Timestamp,
+    TimestampFmt(String),
+}
+
+/// A value produced by coercing a raw string via a [`Conversion`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bytes(Vec<u8>),
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Timestamp(DateTime<Utc>),
+}
+
+/// Raised when a captured attribute or payload string doesn't match the
+/// `Conversion` an `Action` declared for it, so the caller can feed a precise
+/// correction back to the model instead of a generic parse failure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionError {
+    pub name: String,
+    pub expected: Conversion,
+    pub found: String,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "'{}' expected a value convertible to {:?}, found '{}'",
+            self.name, self.expected, self.found
+        )
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+/// Key under which the payload's `Conversion` is looked up in an action's
+/// attribute schema, since the payload isn't a named attribute.
+pub const PAYLOAD_KEY: &str = "__payload__";
+
+pub fn convert(name: &str, raw: &str, conversion: &Conversion) -> Result<Value, ConversionError> {
+    let invalid = || ConversionError {
+        name: name.to_string(),
+        expected: conversion.clone(),
+        found: raw.to_string(),
+    };
+
+    match conversion {
+        Conversion::Bytes => Ok(Value::Bytes(raw.as_bytes().to_vec())),
+        Conversion::String => Ok(Value::String(raw.to_string())),
+        Conversion::Integer => raw.trim().parse::<i64>().map(Value::Integer).map_err(|_| invalid()),
+        Conversion::Float => raw.trim().parse::<f64>().map(Value::Float).map_err(|_| invalid()),
+        Conversion::Boolean => match raw.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" => Ok(Value::Boolean(true)),
+            "false" | "0" | "no" => Ok(Value::Boolean(false)),
+            _ => Err(invalid()),
+        },
+        Conversion::Timestamp => DateTime::parse_from_rfc3339(raw.trim())
+            .map(|dt| Value::Timestamp(dt.with_timezone(&Utc)))
+            .map_err(|_| invalid()),
+        Conversion::TimestampFmt(format) => NaiveDateTime::parse_from_str(raw.trim(), format)
+            .map(|naive| Value::Timestamp(DateTime::from_naive_utc_and_offset(naive, Utc)))
+            .map_err(|_| invalid()),
+    }
+}
+
+/// Coerces every attribute (and the payload, under [`PAYLOAD_KEY`]) of `inv`
+/// that has an entry in `schema`, returning all conversion failures at once
+/// rather than bailing on the first one.
+pub fn coerce_invocation(
+    inv: &Invocation,
+    schema: &HashMap<String, Conversion>,
+) -> Result<HashMap<String, Value>, Vec<ConversionError>> {
+    let mut values = HashMap::new();
+    let mut errors = Vec::new();
+
+    if let Some(attrs) = &inv.attributes {
+        for (name, raw) in attrs {
+            if let Some(conversion) = schema.get(name) {
+                match convert(name, raw, conversion) {
+                    Ok(value) => {
+                        values.insert(name.clone(), value);
+                    }
+                    Err(err) => errors.push(err),
+                }
+            }
+        }
+    }
+
+    if let Some(payload) = &inv.payload {
+        if let Some(conversion) = schema.get(PAYLOAD_KEY) {
+            match convert(PAYLOAD_KEY, payload, conversion) {
+                Ok(value) => {
+                    values.insert(PAYLOAD_KEY.to_string(), value);
+                }
+                Err(err) => errors.push(err),
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(values)
+    } else {
+        Err(errors)
+    }
+}
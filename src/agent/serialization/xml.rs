@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::fmt;
 
 use anyhow::Result;
 use lazy_static::lazy_static;
@@ -6,13 +7,47 @@ use regex::Regex;
 
 use crate::agent::{
     namespaces::Action,
-    state::storage::{Storage, StorageType, CURRENT_TAG, PREVIOUS_TAG},
+    state::storage::Storage,
 };
 
+use super::conversion::{coerce_invocation, ConversionError, Value, PAYLOAD_KEY};
 use super::Invocation;
 
 lazy_static! {
-    pub static ref XML_ATTRIBUTES_PARSER: Regex = Regex::new(r#"(?m)(([^=]+)="([^"]+)")"#).unwrap();
+    // Matches a single `name="value"` or `name='value'` pair starting at the
+    // current cursor. Anchored with `^` because we always match against a
+    // slice that starts right where we expect the next attribute to begin.
+    static ref ATTRIBUTE_PARSER: Regex =
+        Regex::new(r#"^\s*([A-Za-z_][\w:.\-]*)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+}
+
+/// A single, localized failure while scanning a model response for tool
+/// invocations. `position` is the byte offset of the `<` that triggered the
+/// failed parse (for a malformed attribute, the offset of that attribute), so
+/// a caller can point the model back at the offending fragment instead of
+/// just saying "something was wrong".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlParseError {
+    pub position: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for XmlParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "xml parse error at byte {}: {}", self.position, self.reason)
+    }
+}
+
+impl std::error::Error for XmlParseError {}
+
+/// Result of scanning a model response for tool invocations. Malformed blocks
+/// don't abort the whole scan: they're recorded here so the caller can still
+/// act on whatever invocations did parse, while feeding the errors back to
+/// the model.
+#[derive(Debug, Default)]
+pub(crate) struct ParsedResponse {
+    pub invocations: Vec<Invocation>,
+    pub errors: Vec<XmlParseError>,
 }
 
 pub(crate) fn serialize_invocation(inv: &Invocation) -> String {
@@ -55,142 +90,319 @@ pub(crate) fn serialize_action(action: &Box<dyn Action>) -> String {
     xml
 }
 
+/// Coerces each invocation in `response` against the [`Conversion`] schema
+/// its matching `Action` declares (attributes via `attribute_types`, the
+/// payload under [`PAYLOAD_KEY`] via `payload_type`), pairing every matched
+/// invocation with its coercion result. Invocations whose action isn't found
+/// in `actions` are skipped rather than erroring, since an unrecognized tag
+/// is a separate problem from a type mismatch.
+pub(crate) fn coerce_response<'a>(
+    response: &'a ParsedResponse,
+    actions: &[Box<dyn Action>],
+) -> Vec<(&'a Invocation, Result<HashMap<String, Value>, Vec<ConversionError>>)> {
+    response
+        .invocations
+        .iter()
+        .filter_map(|inv| {
+            let action = actions.iter().find(|a| a.name() == inv.action)?;
+
+            let mut schema = action.attribute_types();
+            if let Some(payload_type) = action.payload_type() {
+                schema.insert(PAYLOAD_KEY.to_string(), payload_type);
+            }
+
+            Some((inv, coerce_invocation(inv, &schema)))
+        })
+        .collect()
+}
+
+/// Thin wrapper kept for callers that still go through `serialize_storage`
+/// rather than calling `Storage::to_structured_string` directly. `Storage`
+/// already iterates its backend via the `StorageBackend` trait, so there's
+/// no map to lock or entry format to duplicate here.
 pub(crate) fn serialize_storage(storage: &Storage) -> String {
-    let inner = storage.get_inner().lock().unwrap();
-    if inner.is_empty() {
-        return "".to_string();
-    }
+    storage.to_structured_string()
+}
 
-    match storage.get_type() {
-        StorageType::Tagged => {
-            let mut xml: String = format!("<{}>\n", storage.get_name());
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | ':' | '.')
+}
 
-            for (key, entry) in &*inner {
-                xml += &format!("  - {}={}\n", key, &entry.data);
-            }
+// Short, human-readable snippet of a failing region, used in error messages.
+fn preview(s: &str) -> String {
+    let snippet: String = s.chars().take(24).collect();
+    if snippet.len() < s.len() {
+        format!("{snippet}...")
+    } else {
+        snippet
+    }
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "amp" => Some('&'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+            u32::from_str_radix(&entity[2..], 16).ok().and_then(char::from_u32)
+        }
+        _ if entity.starts_with('#') => entity[1..].parse::<u32>().ok().and_then(char::from_u32),
+        _ => None,
+    }
+}
 
-            xml += &format!("</{}>", storage.get_name());
+fn unescape_entities(input: &str) -> String {
+    if !input.contains('&') {
+        return input.to_string();
+    }
 
-            xml.to_string()
+    let mut out = String::with_capacity(input.len());
+    let mut pos = 0;
+    while pos < input.len() {
+        if input.as_bytes()[pos] == b'&' {
+            if let Some(end) = input[pos..].find(';').map(|p| pos + p) {
+                if let Some(replacement) = decode_entity(&input[pos + 1..end]) {
+                    out.push(replacement);
+                    pos = end + 1;
+                    continue;
+                }
+            }
         }
-        StorageType::Untagged => {
-            let mut xml = format!("<{}>\n", storage.get_name());
+        let ch = input[pos..].chars().next().unwrap();
+        out.push(ch);
+        pos += ch.len_utf8();
+    }
+    out
+}
 
-            for entry in inner.values() {
-                xml += &format!("  - {}\n", &entry.data);
+// Unescapes entities in the text portions of a payload while leaving any
+// `<![CDATA[ ... ]]>` sections verbatim, so payloads can carry raw markup or
+// code without it being mangled.
+fn decode_payload(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut pos = 0;
+
+    while pos < raw.len() {
+        if let Some(rel) = raw[pos..].find("<![CDATA[") {
+            let cdata_start = pos + rel;
+            out += &unescape_entities(&raw[pos..cdata_start]);
+
+            let content_start = cdata_start + "<![CDATA[".len();
+            match raw[content_start..].find("]]>") {
+                Some(end_rel) => {
+                    let content_end = content_start + end_rel;
+                    out += &raw[content_start..content_end];
+                    pos = content_end + "]]>".len();
+                }
+                None => {
+                    out += &raw[content_start..];
+                    pos = raw.len();
+                }
             }
+        } else {
+            out += &unescape_entities(&raw[pos..]);
+            pos = raw.len();
+        }
+    }
 
-            xml += &format!("</{}>", storage.get_name());
+    out
+}
 
-            xml.to_string()
-        }
-        StorageType::Completion => {
-            let mut xml = format!("<{}>\n", storage.get_name());
-
-            for entry in inner.values() {
-                xml += &format!(
-                    "  - {} : {}\n",
-                    &entry.data,
-                    if entry.complete {
-                        "COMPLETED"
-                    } else {
-                        "not completed"
-                    }
-                );
-            }
+// The parsed `name="value"`/`name='value'` pairs of an opening tag, plus
+// where its header ends. Shared by `parse_tag_at` and `find_matching_close`
+// so both agree on where a `>` inside a quoted attribute value doesn't count
+// as the tag's real close.
+struct TagHeader {
+    attributes: HashMap<String, String>,
+    self_closing: bool,
+    // Byte offset right after the header's closing `>` (or the `>` of `/>`).
+    end: usize,
+}
 
-            xml += &format!("</{}>", storage.get_name());
+// Scans an opening tag's attributes starting right after its name, quote-aware
+// so a literal `>` or `/>` inside an attribute value isn't mistaken for the
+// tag's close. `tag_start` and `name` are only used for error messages.
+fn scan_tag_header(src: &str, after_name: usize, tag_start: usize, name: &str) -> Result<TagHeader, XmlParseError> {
+    let mut cursor = after_name;
+    let mut attributes: HashMap<String, String> = HashMap::new();
+    let self_closing;
+
+    loop {
+        let trimmed = src[cursor..].trim_start();
+        cursor = src.len() - trimmed.len();
+
+        if cursor >= src.len() {
+            return Err(XmlParseError {
+                position: tag_start,
+                reason: format!("unterminated opening tag <{name}>"),
+            });
+        }
 
-            xml.to_string()
+        if src[cursor..].starts_with("/>") {
+            self_closing = true;
+            cursor += 2;
+            break;
         }
-        StorageType::CurrentPrevious => {
-            if let Some(current) = inner.get(CURRENT_TAG) {
-                let mut str = format!("* Current {}: {}", storage.get_name(), current.data.trim());
-                if let Some(prev) = inner.get(PREVIOUS_TAG) {
-                    str += &format!("\n* Previous {}: {}", storage.get_name(), prev.data.trim());
-                }
-                str
-            } else {
-                "".to_string()
+        if src[cursor..].starts_with('>') {
+            self_closing = false;
+            cursor += 1;
+            break;
+        }
+
+        match ATTRIBUTE_PARSER.captures(&src[cursor..]) {
+            Some(caps) => {
+                let whole = caps.get(0).unwrap();
+                let key = caps.get(1).unwrap().as_str().to_string();
+                let raw_value = caps.get(2).or_else(|| caps.get(3)).unwrap().as_str();
+                attributes.insert(key, unescape_entities(raw_value));
+                cursor += whole.end();
+            }
+            None => {
+                return Err(XmlParseError {
+                    position: cursor,
+                    reason: format!("malformed attribute near '{}'", preview(&src[cursor..])),
+                });
             }
         }
     }
+
+    Ok(TagHeader {
+        attributes,
+        self_closing,
+        end: cursor,
+    })
 }
 
-pub(crate) fn parse_model_response(model_response: &str) -> Result<Vec<Invocation>> {
-    let mut invocations = vec![];
-
-    let model_response_size = model_response.len();
-    let mut current = 0;
-
-    // TODO: replace this with a proper xml parser
-    while current < model_response_size {
-        // read until < or end
-        let mut ptr = &model_response[current..];
-        if let Some(tag_open_idx) = ptr.find('<') {
-            current += tag_open_idx;
-            ptr = &ptr[tag_open_idx..];
-            // read tag
-            if let Some(tag_name_term_idx) = ptr.find(|c: char| c == '>' || c == ' ') {
-                current += tag_name_term_idx;
-                let tag_name = &ptr[1..tag_name_term_idx];
-                // println!("tag_name={}", tag_name);
-                if let Some(tag_close_idx) = ptr.find('>') {
-                    current += tag_close_idx + tag_name.len();
-                    let tag_closing = format!("</{}>", tag_name);
-                    let tag_closing_idx = ptr.find(&tag_closing);
-
-                    if let Some(tag_closing_idx) = tag_closing_idx {
-                        // parse attributes if any
-                        let attributes = if ptr.as_bytes()[tag_name_term_idx] == b' ' {
-                            let attr_str = &ptr[tag_name_term_idx + 1..tag_close_idx];
-                            let mut attrs = HashMap::new();
-
-                            // parse as a list of key="value"
-                            let iter = XML_ATTRIBUTES_PARSER.captures_iter(attr_str);
-                            for caps in iter {
-                                if caps.len() == 4 {
-                                    let key = caps.get(2).unwrap().as_str().trim();
-                                    let value = caps.get(3).unwrap().as_str().trim();
-                                    attrs.insert(key.to_string(), value.to_string());
-                                }
-                            }
-
-                            Some(attrs)
-                        } else {
-                            None
-                        };
-
-                        // parse payload if any
-                        let after_tag_close = &ptr[tag_close_idx + 1..tag_closing_idx];
-                        let payload = if !after_tag_close.is_empty() {
-                            if after_tag_close.as_bytes()[0] != b'<' {
-                                Some(after_tag_close.trim().to_string())
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        };
-
-                        invocations.push(Invocation::new(
-                            tag_name.to_string(),
-                            attributes,
-                            payload,
-                        ));
+// Scans forward from `pos` for the closing tag matching `name`, skipping over
+// CDATA blocks verbatim and tracking nesting depth (via the same quote-aware
+// header scan `parse_tag_at` uses) so a payload is free to contain further
+// tags with the same name, including ones whose attributes embed `>` or `/>`.
+// Returns the byte offset of the `<` that starts the matching `</name>`.
+fn find_matching_close(src: &str, mut pos: usize, name: &str) -> Option<usize> {
+    let open_prefix = format!("<{name}");
+    let close_tag = format!("</{name}>");
+    let mut depth = 1i32;
+
+    loop {
+        let next_lt = pos + src[pos..].find('<')?;
+
+        if src[next_lt..].starts_with("<![CDATA[") {
+            let rel = src[next_lt..].find("]]>")?;
+            pos = next_lt + rel + "]]>".len();
+            continue;
+        }
 
+        if src[next_lt..].starts_with(&close_tag) {
+            depth -= 1;
+            if depth == 0 {
+                return Some(next_lt);
+            }
+            pos = next_lt + close_tag.len();
+            continue;
+        }
+
+        if src[next_lt..].starts_with(&open_prefix) {
+            let after = &src[next_lt + open_prefix.len()..];
+            // Matches the whitespace `scan_tag_header`'s `trim_start()` skips,
+            // so a same-named nested tag whose attributes wrap onto the next
+            // line is still recognized as a real nested open, not plain text.
+            let is_boundary = after.starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace());
+            if is_boundary {
+                match scan_tag_header(src, next_lt + open_prefix.len(), next_lt, name) {
+                    Ok(header) => {
+                        if !header.self_closing {
+                            depth += 1;
+                        }
+                        pos = header.end;
+                        continue;
+                    }
+                    Err(_) => {
+                        // Malformed nested header: treat the `<` as plain
+                        // text and keep scanning rather than failing the
+                        // whole outer tag over it.
+                        pos = next_lt + 1;
                         continue;
                     }
                 }
             }
+        }
 
-            // just skip ahead
-            current += 1;
-        } else {
-            // no more tags
-            break;
+        pos = next_lt + 1;
+    }
+}
+
+// Attempts to parse a single tag starting at `src[start]` (which must be
+// `<`). Returns `Ok(None)` when the `<` doesn't begin a plausible action tag
+// (e.g. a stray closing tag, a comment, or a processing instruction) so the
+// caller can just skip past it rather than treating it as an error.
+fn parse_tag_at(src: &str, start: usize) -> Result<Option<(Invocation, usize)>, XmlParseError> {
+    let rest = &src[start + 1..];
+    let first = match rest.chars().next() {
+        Some(c) => c,
+        None => return Ok(None),
+    };
+    if !(first.is_alphabetic() || first == '_') {
+        return Ok(None);
+    }
+
+    let name_len = rest.find(|c: char| !is_name_char(c)).unwrap_or(rest.len());
+    let name = &rest[..name_len];
+    let name_end = start + 1 + name_len;
+
+    let header = scan_tag_header(src, name_end, start, name)?;
+    let attributes = if header.attributes.is_empty() {
+        None
+    } else {
+        Some(header.attributes)
+    };
+
+    if header.self_closing {
+        return Ok(Some((Invocation::new(name.to_string(), attributes, None), header.end)));
+    }
+
+    match find_matching_close(src, header.end, name) {
+        Some(close_start) => {
+            let payload = decode_payload(&src[header.end..close_start]);
+            let payload = if payload.trim().is_empty() {
+                None
+            } else {
+                Some(payload.trim().to_string())
+            };
+            let close_end = close_start + format!("</{name}>").len();
+
+            Ok(Some((Invocation::new(name.to_string(), attributes, payload), close_end)))
         }
+        None => Err(XmlParseError {
+            position: start,
+            reason: format!("no matching closing tag found for <{name}>"),
+        }),
     }
+}
+
+pub(crate) fn parse_model_response(model_response: &str) -> Result<ParsedResponse> {
+    let mut response = ParsedResponse::default();
+    let mut cursor = 0;
+
+    while cursor < model_response.len() {
+        let tag_start = match model_response[cursor..].find('<') {
+            Some(rel) => cursor + rel,
+            None => break,
+        };
 
-    Ok(invocations)
-}
\ No newline at end of file
+        match parse_tag_at(model_response, tag_start) {
+            Ok(Some((invocation, next))) => {
+                response.invocations.push(invocation);
+                cursor = next;
+            }
+            Ok(None) => cursor = tag_start + 1,
+            Err(err) => {
+                response.errors.push(err);
+                cursor = tag_start + 1;
+            }
+        }
+    }
+
+    Ok(response)
+}
@@ -1,7 +1,21 @@
 use indexmap::IndexMap;
-use std::{sync::Mutex /* , time::SystemTime*/};
+use std::{
+    fs,
+    io::{self, Error, ErrorKind},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+    /* , time::SystemTime*/
+};
 
+use argon2::Argon2;
+use async_trait::async_trait;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
 use colored::Colorize;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use tracing::{debug, error, info};
 
 // TODO: investigate other uses of IndexMap around the project
 
@@ -18,6 +32,147 @@ impl Entry {
     }
 }
 
+/// The storage side of a single key/value pair, as handed across the
+/// `StorageBackend` boundary. Kept separate from the private `Entry` type so
+/// backends don't need to depend on `Storage`'s internals.
+pub type StoredValue = String;
+
+/// Abstracts the map a `Storage` reads and writes, so the default in-memory
+/// `IndexMap` can be swapped for a durable or shared backend (SQLite, Redis,
+/// ...) without touching `Storage`'s typed helper methods.
+///
+/// Implementations must preserve insertion order: `Untagged` positional keys
+/// and `CurrentPrevious` rotation both depend on it.
+pub trait StorageBackend: Send + Sync {
+    fn get(&self, key: &str) -> Option<StoredValue>;
+    /// Inserts `data` under `key`, returning the previous value if any.
+    fn insert(&self, key: String, data: StoredValue) -> Option<StoredValue>;
+    fn shift_remove(&self, key: &str) -> Option<StoredValue>;
+    /// Entries in insertion order.
+    fn iter(&self) -> Vec<(String, StoredValue)>;
+    /// Removes every entry, returning what was removed in insertion order.
+    fn clear(&self) -> Vec<(String, StoredValue)>;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Async counterpart of [`StorageBackend`], for backends whose reads/writes
+/// go over the network (a remote cache, a multi-agent shared store, ...) and
+/// shouldn't block a worker thread. Mirrors the sync/async client split used
+/// elsewhere in the project.
+#[async_trait]
+pub trait AsyncStorageBackend: Send + Sync {
+    async fn get(&self, key: &str) -> Option<StoredValue>;
+    async fn insert(&self, key: String, data: StoredValue) -> Option<StoredValue>;
+    async fn shift_remove(&self, key: &str) -> Option<StoredValue>;
+    async fn iter(&self) -> Vec<(String, StoredValue)>;
+    async fn clear(&self) -> Vec<(String, StoredValue)>;
+    async fn len(&self) -> usize;
+    async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+}
+
+/// The zero-config default backend: an in-memory, insertion-ordered map
+/// behind a mutex. What `Storage` used before backends were pluggable.
+#[derive(Debug, Default)]
+pub struct IndexMapBackend {
+    inner: Mutex<IndexMap<String, Entry>>,
+}
+
+impl IndexMapBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StorageBackend for IndexMapBackend {
+    fn get(&self, key: &str) -> Option<StoredValue> {
+        self.inner.lock().unwrap().get(key).map(|e| e.data.clone())
+    }
+
+    fn insert(&self, key: String, data: StoredValue) -> Option<StoredValue> {
+        self.inner
+            .lock()
+            .unwrap()
+            .insert(key, Entry::new(data))
+            .map(|old| old.data)
+    }
+
+    fn shift_remove(&self, key: &str) -> Option<StoredValue> {
+        self.inner.lock().unwrap().shift_remove(key).map(|old| old.data)
+    }
+
+    fn iter(&self) -> Vec<(String, StoredValue)> {
+        self.inner
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.data.clone()))
+            .collect()
+    }
+
+    fn clear(&self) -> Vec<(String, StoredValue)> {
+        self.inner
+            .lock()
+            .unwrap()
+            .drain(..)
+            .map(|(k, v)| (k, v.data))
+            .collect()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.lock().unwrap().len()
+    }
+}
+
+/// Async mirror of [`IndexMapBackend`], for call sites that already run on
+/// an async runtime and would rather `.await` a lock than block on one.
+#[derive(Debug, Default)]
+pub struct AsyncIndexMapBackend {
+    inner: tokio::sync::Mutex<IndexMap<String, Entry>>,
+}
+
+impl AsyncIndexMapBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl AsyncStorageBackend for AsyncIndexMapBackend {
+    async fn get(&self, key: &str) -> Option<StoredValue> {
+        self.inner.lock().await.get(key).map(|e| e.data.clone())
+    }
+
+    async fn insert(&self, key: String, data: StoredValue) -> Option<StoredValue> {
+        self.inner.lock().await.insert(key, Entry::new(data)).map(|old| old.data)
+    }
+
+    async fn shift_remove(&self, key: &str) -> Option<StoredValue> {
+        self.inner.lock().await.shift_remove(key).map(|old| old.data)
+    }
+
+    async fn iter(&self) -> Vec<(String, StoredValue)> {
+        self.inner
+            .lock()
+            .await
+            .iter()
+            .map(|(k, v)| (k.clone(), v.data.clone()))
+            .collect()
+    }
+
+    async fn clear(&self) -> Vec<(String, StoredValue)> {
+        self.inner.lock().await.drain(..).map(|(k, v)| (k, v.data)).collect()
+    }
+
+    async fn len(&self) -> usize {
+        self.inner.lock().await.len()
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum StorageType {
     // a list indexed by element position
@@ -36,23 +191,232 @@ impl StorageType {
             StorageType::Tagged => 2,
         }
     }
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(StorageType::CurrentPrevious),
+            1 => Some(StorageType::Untagged),
+            2 => Some(StorageType::Tagged),
+            _ => None,
+        }
+    }
 }
 
 const CURRENT_TAG: &str = "__current";
 const PREVIOUS_TAG: &str = "__previous";
 
-#[derive(Debug)]
+/// A callback invoked as `(key, old_data, new_data)` after a mutation
+/// commits. `old_data`/`new_data` are `None` depending on the event: a `put`
+/// has no old value, a `remove` has no new value, a `replace` has both.
+/// `Arc`, not `Box`, so a hook list can be cloned out from under its mutex
+/// before firing.
+type Hook = Arc<dyn Fn(&str, Option<&str>, Option<&str>) + Send + Sync>;
+
+#[derive(Default)]
+struct Hooks {
+    on_put: Mutex<Vec<Hook>>,
+    on_remove: Mutex<Vec<Hook>>,
+    on_replace: Mutex<Vec<Hook>>,
+}
+
+impl Hooks {
+    // `std::sync::Mutex` isn't reentrant: a hook body that registers another
+    // hook, or triggers another mutation on the same store, would deadlock
+    // the thread if invoked while still holding the lock. Clone the list out
+    // and drop the guard first so hook bodies are free to call back in.
+    fn fire_put(&self, key: &str, new: &str) {
+        let hooks = self.on_put.lock().unwrap().clone();
+        for hook in &hooks {
+            hook(key, None, Some(new));
+        }
+    }
+
+    fn fire_remove(&self, key: &str, old: &str) {
+        let hooks = self.on_remove.lock().unwrap().clone();
+        for hook in &hooks {
+            hook(key, Some(old), None);
+        }
+    }
+
+    fn fire_replace(&self, key: &str, old: &str, new: &str) {
+        let hooks = self.on_replace.lock().unwrap().clone();
+        for hook in &hooks {
+            hook(key, Some(old), Some(new));
+        }
+    }
+}
+
+/// Which kind of mutation produced a [`StorageEvent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageEventKind {
+    Put,
+    Remove,
+    Replace,
+    Clear,
+}
+
+/// A structured record of a single `Storage` mutation, replacing the
+/// `println!`s that used to fire directly from `add_tagged`/`set_current`/...
+/// Emitted as a `tracing` event (filterable via `RUST_LOG`) and handed to the
+/// store's [`EventSink`] for anything console- or transcript-shaped
+/// (`set_current` hands its event to the sink only when called with `verbose`).
+#[derive(Debug, Clone)]
+pub struct StorageEvent {
+    pub store: String,
+    pub store_type: StorageType,
+    pub kind: StorageEventKind,
+    pub key: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+fn trace_event(event: &StorageEvent) {
+    match event.kind {
+        StorageEventKind::Put | StorageEventKind::Replace => info!(
+            store = %event.store,
+            key = %event.key,
+            old = event.old_value.as_deref(),
+            new = event.new_value.as_deref(),
+            "storage event"
+        ),
+        StorageEventKind::Remove | StorageEventKind::Clear => debug!(
+            store = %event.store,
+            key = %event.key,
+            old = event.old_value.as_deref(),
+            "storage event"
+        ),
+    }
+}
+
+/// Where a [`StorageEvent`] ends up once it's emitted, on top of the always-on
+/// `tracing` record. Swap in [`NullSink`] to silence console output entirely,
+/// or a custom sink to capture events into a transcript.
+///
+/// `tracing` output itself is controlled the usual way: initialize a
+/// subscriber (e.g. `tracing_subscriber::fmt()` with `EnvFilter::from_default_env()`
+/// and a humantime timestamp formatter) in the binary, and filter with
+/// `RUST_LOG`.
+pub trait EventSink: Send + Sync {
+    fn record(&self, event: &StorageEvent);
+}
+
+/// Reproduces the colored stdout lines `Storage` used to print directly, and
+/// is installed by default so existing behavior doesn't change out of the box.
+pub struct ConsoleSink;
+
+// `CurrentPrevious` stores key their single slot under the internal
+// `CURRENT_TAG`/`PREVIOUS_TAG` constants; console output should say "current"
+// rather than leak that literal.
+fn display_key(event: &StorageEvent) -> &str {
+    if event.key == CURRENT_TAG {
+        "current"
+    } else if event.key == PREVIOUS_TAG {
+        "previous"
+    } else {
+        &event.key
+    }
+}
+
+impl EventSink for ConsoleSink {
+    fn record(&self, event: &StorageEvent) {
+        match event.kind {
+            StorageEventKind::Put | StorageEventKind::Replace => {
+                if matches!(event.store_type, StorageType::Untagged) {
+                    println!("<{}> {}", event.store.bold(), event.new_value.as_deref().unwrap_or("").yellow())
+                } else {
+                    println!(
+                        "<{}> {}={}",
+                        event.store.bold(),
+                        display_key(event),
+                        event.new_value.as_deref().unwrap_or("").yellow()
+                    )
+                }
+            }
+            StorageEventKind::Remove => {
+                if matches!(event.store_type, StorageType::Untagged) {
+                    println!("<{}> element {} removed\n", event.store.bold(), display_key(event))
+                } else {
+                    println!("<{}> {} removed\n", event.store.bold(), display_key(event))
+                }
+            }
+            StorageEventKind::Clear => println!("<{}> cleared", event.store.bold()),
+        }
+    }
+}
+
+/// Discards every event. Useful for library consumers who only want the
+/// `tracing` records, or none at all.
+pub struct NullSink;
+
+impl EventSink for NullSink {
+    fn record(&self, _event: &StorageEvent) {}
+}
+
 pub struct Storage {
     name: String,
     type_: StorageType,
-    inner: Mutex<IndexMap<String, Entry>>,
+    backend: Box<dyn StorageBackend>,
+    hooks: Hooks,
+    sink: Mutex<Box<dyn EventSink>>,
+}
+
+impl std::fmt::Debug for Storage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Storage")
+            .field("name", &self.name)
+            .field("type_", &self.type_)
+            .finish()
+    }
 }
 
 impl Storage {
+    /// Creates a store backed by the zero-config in-memory `IndexMapBackend`.
     pub fn new(name: &str, type_: StorageType) -> Self {
-        let name = name.to_string();
-        let inner = Mutex::new(IndexMap::new());
-        Self { name, type_, inner }
+        Self::with_backend(name, type_, Box::new(IndexMapBackend::new()))
+    }
+
+    /// Creates a store delegating all reads/writes to `backend`, e.g. a
+    /// SQLite- or Redis-backed implementation of [`StorageBackend`].
+    pub fn with_backend(name: &str, type_: StorageType, backend: Box<dyn StorageBackend>) -> Self {
+        Self {
+            name: name.to_string(),
+            type_,
+            backend,
+            hooks: Hooks::default(),
+            sink: Mutex::new(Box::new(ConsoleSink)),
+        }
+    }
+
+    /// Replaces the sink every put/replace/remove/clear event is routed to. Install
+    /// [`NullSink`] to silence it, or a custom sink to capture events.
+    pub fn set_sink(&self, sink: Box<dyn EventSink>) {
+        *self.sink.lock().unwrap() = sink;
+    }
+
+    /// Registers a callback fired after a new key is inserted into a store
+    /// that didn't previously have it.
+    pub fn on_put<F>(&self, f: F)
+    where
+        F: Fn(&str, Option<&str>, Option<&str>) + Send + Sync + 'static,
+    {
+        self.hooks.on_put.lock().unwrap().push(Arc::new(f));
+    }
+
+    /// Registers a callback fired after a key is removed from a store.
+    pub fn on_remove<F>(&self, f: F)
+    where
+        F: Fn(&str, Option<&str>, Option<&str>) + Send + Sync + 'static,
+    {
+        self.hooks.on_remove.lock().unwrap().push(Arc::new(f));
+    }
+
+    /// Registers a callback fired after an existing key's value is
+    /// overwritten, e.g. `set_current` rotating current into previous.
+    pub fn on_replace<F>(&self, f: F)
+    where
+        F: Fn(&str, Option<&str>, Option<&str>) + Send + Sync + 'static,
+    {
+        self.hooks.on_replace.lock().unwrap().push(Arc::new(f));
     }
 
     pub fn get_type(&self) -> &StorageType {
@@ -60,8 +424,8 @@ impl Storage {
     }
 
     pub fn to_structured_string(&self) -> String {
-        let inner = self.inner.lock().unwrap();
-        if inner.is_empty() {
+        let entries = self.backend.iter();
+        if entries.is_empty() {
             return "".to_string();
         }
 
@@ -69,8 +433,8 @@ impl Storage {
             StorageType::Tagged => {
                 let mut xml: String = format!("<{}>\n", &self.name);
 
-                for (key, entry) in &*inner {
-                    xml += &format!("  - {}={}\n", key, &entry.data);
+                for (key, data) in &entries {
+                    xml += &format!("  - {}={}\n", key, data);
                 }
 
                 xml += &format!("</{}>", &self.name);
@@ -80,8 +444,8 @@ impl Storage {
             StorageType::Untagged => {
                 let mut xml = format!("<{}>\n", &self.name);
 
-                for entry in inner.values() {
-                    xml += &format!("  - {}\n", &entry.data);
+                for (_, data) in &entries {
+                    xml += &format!("  - {}\n", data);
                 }
 
                 xml += &format!("</{}>", &self.name);
@@ -89,10 +453,10 @@ impl Storage {
                 xml.to_string()
             }
             StorageType::CurrentPrevious => {
-                if let Some(current) = inner.get(CURRENT_TAG) {
-                    let mut str = format!("* Current {}: {}", &self.name, current.data.trim());
-                    if let Some(prev) = inner.get(PREVIOUS_TAG) {
-                        str += &format!("\n* Previous {}: {}", &self.name, prev.data.trim());
+                if let Some((_, current)) = entries.iter().find(|(k, _)| k == CURRENT_TAG) {
+                    let mut str = format!("* Current {}: {}", &self.name, current.trim());
+                    if let Some((_, prev)) = entries.iter().find(|(k, _)| k == PREVIOUS_TAG) {
+                        str += &format!("\n* Previous {}: {}", &self.name, prev.trim());
                     }
                     str
                 } else {
@@ -104,18 +468,41 @@ impl Storage {
 
     pub fn add_tagged(&self, key: &str, data: &str) {
         assert!(matches!(self.type_, StorageType::Tagged));
-        println!("<{}> {}={}", self.name.bold(), key, data.yellow());
-        self.inner
-            .lock()
-            .unwrap()
-            .insert(key.to_string(), Entry::new(data.to_string()));
+        let old = self.backend.insert(key.to_string(), data.to_string());
+
+        let event = StorageEvent {
+            store: self.name.clone(),
+            store_type: self.type_,
+            kind: if old.is_some() { StorageEventKind::Replace } else { StorageEventKind::Put },
+            key: key.to_string(),
+            old_value: old.clone(),
+            new_value: Some(data.to_string()),
+        };
+        trace_event(&event);
+        self.sink.lock().unwrap().record(&event);
+
+        match old {
+            Some(old) => self.hooks.fire_replace(key, &old, data),
+            None => self.hooks.fire_put(key, data),
+        }
     }
 
     pub fn del_tagged(&self, key: &str) -> Option<String> {
         assert!(matches!(self.type_, StorageType::Tagged));
-        if let Some(old) = self.inner.lock().unwrap().shift_remove(key) {
-            println!("<{}> {} removed\n", self.name.bold(), key);
-            Some(old.data)
+        if let Some(old) = self.backend.shift_remove(key) {
+            let event = StorageEvent {
+                store: self.name.clone(),
+                store_type: self.type_,
+                kind: StorageEventKind::Remove,
+                key: key.to_string(),
+                old_value: Some(old.clone()),
+                new_value: None,
+            };
+            trace_event(&event);
+            self.sink.lock().unwrap().record(&event);
+
+            self.hooks.fire_remove(key, &old);
+            Some(old)
         } else {
             None
         }
@@ -123,29 +510,49 @@ impl Storage {
 
     pub fn get_tagged(&self, key: &str) -> Option<String> {
         assert!(matches!(self.type_, StorageType::Tagged));
-        self.inner
-            .lock()
-            .unwrap()
-            .get(key)
-            .map(|va| va.data.to_string())
+        self.backend.get(key)
     }
 
     pub fn add_untagged(&self, data: &str) {
         assert!(matches!(self.type_, StorageType::Untagged));
-        println!("<{}> {}", self.name.bold(), data.yellow());
 
-        let mut inner = self.inner.lock().unwrap();
+        let tag = format!("{}", self.backend.len() + 1);
+        let old = self.backend.insert(tag.clone(), data.to_string());
+
+        let event = StorageEvent {
+            store: self.name.clone(),
+            store_type: self.type_,
+            kind: if old.is_some() { StorageEventKind::Replace } else { StorageEventKind::Put },
+            key: tag.clone(),
+            old_value: old.clone(),
+            new_value: Some(data.to_string()),
+        };
+        trace_event(&event);
+        self.sink.lock().unwrap().record(&event);
 
-        let tag = format!("{}", inner.len() + 1);
-        inner.insert(tag, Entry::new(data.to_string()));
+        match old {
+            Some(old) => self.hooks.fire_replace(&tag, &old, data),
+            None => self.hooks.fire_put(&tag, data),
+        }
     }
 
     pub fn del_untagged(&self, pos: usize) -> Option<String> {
         assert!(matches!(self.type_, StorageType::Untagged));
         let tag = format!("{}", pos);
-        if let Some(old) = self.inner.lock().unwrap().shift_remove(&tag) {
-            println!("<{}> element {} removed\n", self.name.bold(), pos);
-            Some(old.data)
+        if let Some(old) = self.backend.shift_remove(&tag) {
+            let event = StorageEvent {
+                store: self.name.clone(),
+                store_type: self.type_,
+                kind: StorageEventKind::Remove,
+                key: tag.clone(),
+                old_value: Some(old.clone()),
+                new_value: None,
+            };
+            trace_event(&event);
+            self.sink.lock().unwrap().record(&event);
+
+            self.hooks.fire_remove(&tag, &old);
+            Some(old)
         } else {
             None
         }
@@ -153,22 +560,462 @@ impl Storage {
 
     pub fn set_current(&self, data: &str, verbose: bool) {
         assert!(matches!(self.type_, StorageType::CurrentPrevious));
-        let mut inner = self.inner.lock().unwrap();
 
-        if verbose {
-            println!("<{}> current={}", self.name.bold(), data.yellow());
+        let old_current = self.backend.shift_remove(CURRENT_TAG);
+        self.backend.insert(CURRENT_TAG.to_string(), data.to_string());
+        if let Some(old_curr) = &old_current {
+            self.backend.insert(PREVIOUS_TAG.to_string(), old_curr.clone());
         }
 
-        let old_current = inner.shift_remove(CURRENT_TAG);
+        let event = StorageEvent {
+            store: self.name.clone(),
+            store_type: self.type_,
+            kind: if old_current.is_some() { StorageEventKind::Replace } else { StorageEventKind::Put },
+            key: CURRENT_TAG.to_string(),
+            old_value: old_current.clone(),
+            new_value: Some(data.to_string()),
+        };
+        trace_event(&event);
+        if verbose {
+            self.sink.lock().unwrap().record(&event);
+        }
 
-        inner.insert(CURRENT_TAG.to_string(), Entry::new(data.to_string()));
-        if let Some(old_curr) = old_current {
-            inner.insert(PREVIOUS_TAG.to_string(), old_curr);
+        match old_current {
+            Some(old) => self.hooks.fire_replace(CURRENT_TAG, &old, data),
+            None => self.hooks.fire_put(CURRENT_TAG, data),
         }
     }
 
     pub fn clear(&self) {
-        self.inner.lock().unwrap().clear();
-        println!("<{}> cleared", self.name.bold());
+        let drained = self.backend.clear();
+
+        let event = StorageEvent {
+            store: self.name.clone(),
+            store_type: self.type_,
+            kind: StorageEventKind::Clear,
+            key: "*".to_string(),
+            old_value: None,
+            new_value: None,
+        };
+        trace_event(&event);
+        self.sink.lock().unwrap().record(&event);
+
+        for (key, data) in drained {
+            self.hooks.fire_remove(&key, &data);
+        }
+    }
+}
+
+/// Async mirror of [`Storage`], for callers already on an async runtime that
+/// want to plug in an [`AsyncStorageBackend`] (a remote cache, a shared
+/// multi-agent store, ...) without blocking a worker thread on a `Mutex`.
+/// Keeps the same typed helper methods as `Storage`, just `async`; hooks and
+/// pluggable sinks aren't mirrored here, since nothing async-backed needs
+/// them yet.
+pub struct AsyncStorage {
+    name: String,
+    type_: StorageType,
+    backend: Box<dyn AsyncStorageBackend>,
+}
+
+impl std::fmt::Debug for AsyncStorage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncStorage")
+            .field("name", &self.name)
+            .field("type_", &self.type_)
+            .finish()
+    }
+}
+
+impl AsyncStorage {
+    /// Creates a store backed by the zero-config [`AsyncIndexMapBackend`].
+    pub fn new(name: &str, type_: StorageType) -> Self {
+        Self::with_backend(name, type_, Box::new(AsyncIndexMapBackend::new()))
+    }
+
+    /// Creates a store delegating all reads/writes to `backend`, e.g. a
+    /// remote-cache-backed implementation of [`AsyncStorageBackend`].
+    pub fn with_backend(name: &str, type_: StorageType, backend: Box<dyn AsyncStorageBackend>) -> Self {
+        Self {
+            name: name.to_string(),
+            type_,
+            backend,
+        }
+    }
+
+    pub fn get_type(&self) -> &StorageType {
+        &self.type_
+    }
+
+    pub async fn to_structured_string(&self) -> String {
+        let entries = self.backend.iter().await;
+        if entries.is_empty() {
+            return "".to_string();
+        }
+
+        match self.type_ {
+            StorageType::Tagged => {
+                let mut xml: String = format!("<{}>\n", &self.name);
+
+                for (key, data) in &entries {
+                    xml += &format!("  - {}={}\n", key, data);
+                }
+
+                xml += &format!("</{}>", &self.name);
+
+                xml.to_string()
+            }
+            StorageType::Untagged => {
+                let mut xml = format!("<{}>\n", &self.name);
+
+                for (_, data) in &entries {
+                    xml += &format!("  - {}\n", data);
+                }
+
+                xml += &format!("</{}>", &self.name);
+
+                xml.to_string()
+            }
+            StorageType::CurrentPrevious => {
+                if let Some((_, current)) = entries.iter().find(|(k, _)| k == CURRENT_TAG) {
+                    let mut str = format!("* Current {}: {}", &self.name, current.trim());
+                    if let Some((_, prev)) = entries.iter().find(|(k, _)| k == PREVIOUS_TAG) {
+                        str += &format!("\n* Previous {}: {}", &self.name, prev.trim());
+                    }
+                    str
+                } else {
+                    "".to_string()
+                }
+            }
+        }
+    }
+
+    pub async fn add_tagged(&self, key: &str, data: &str) {
+        assert!(matches!(self.type_, StorageType::Tagged));
+        let old = self.backend.insert(key.to_string(), data.to_string()).await;
+        trace_event(&StorageEvent {
+            store: self.name.clone(),
+            store_type: self.type_,
+            kind: if old.is_some() { StorageEventKind::Replace } else { StorageEventKind::Put },
+            key: key.to_string(),
+            old_value: old,
+            new_value: Some(data.to_string()),
+        });
+    }
+
+    pub async fn del_tagged(&self, key: &str) -> Option<String> {
+        assert!(matches!(self.type_, StorageType::Tagged));
+        let old = self.backend.shift_remove(key).await?;
+        trace_event(&StorageEvent {
+            store: self.name.clone(),
+            store_type: self.type_,
+            kind: StorageEventKind::Remove,
+            key: key.to_string(),
+            old_value: Some(old.clone()),
+            new_value: None,
+        });
+        Some(old)
+    }
+
+    pub async fn get_tagged(&self, key: &str) -> Option<String> {
+        assert!(matches!(self.type_, StorageType::Tagged));
+        self.backend.get(key).await
+    }
+
+    pub async fn add_untagged(&self, data: &str) {
+        assert!(matches!(self.type_, StorageType::Untagged));
+
+        let tag = format!("{}", self.backend.len().await + 1);
+        self.backend.insert(tag.clone(), data.to_string()).await;
+        trace_event(&StorageEvent {
+            store: self.name.clone(),
+            store_type: self.type_,
+            kind: StorageEventKind::Put,
+            key: tag,
+            old_value: None,
+            new_value: Some(data.to_string()),
+        });
+    }
+
+    pub async fn del_untagged(&self, pos: usize) -> Option<String> {
+        assert!(matches!(self.type_, StorageType::Untagged));
+        let tag = format!("{}", pos);
+        let old = self.backend.shift_remove(&tag).await?;
+        trace_event(&StorageEvent {
+            store: self.name.clone(),
+            store_type: self.type_,
+            kind: StorageEventKind::Remove,
+            key: tag,
+            old_value: Some(old.clone()),
+            new_value: None,
+        });
+        Some(old)
+    }
+
+    pub async fn set_current(&self, data: &str) {
+        assert!(matches!(self.type_, StorageType::CurrentPrevious));
+
+        let old_current = self.backend.shift_remove(CURRENT_TAG).await;
+        self.backend.insert(CURRENT_TAG.to_string(), data.to_string()).await;
+        if let Some(old_curr) = &old_current {
+            self.backend.insert(PREVIOUS_TAG.to_string(), old_curr.clone()).await;
+        }
+
+        trace_event(&StorageEvent {
+            store: self.name.clone(),
+            store_type: self.type_,
+            kind: if old_current.is_some() { StorageEventKind::Replace } else { StorageEventKind::Put },
+            key: CURRENT_TAG.to_string(),
+            old_value: old_current,
+            new_value: Some(data.to_string()),
+        });
+    }
+
+    pub async fn clear(&self) {
+        self.backend.clear().await;
+        trace_event(&StorageEvent {
+            store: self.name.clone(),
+            store_type: self.type_,
+            kind: StorageEventKind::Clear,
+            key: "*".to_string(),
+            old_value: None,
+            new_value: None,
+        });
+    }
+}
+
+// On-disk snapshot format: a small versioned, length-prefixed encoding so a
+// `Storage` can be restored exactly as it was, including insertion order
+// (which `Untagged` positional keys and `CurrentPrevious` rotation both
+// depend on). Encryption, when a passphrase is supplied, wraps the encoded
+// payload rather than replacing the format, so unencrypted and encrypted
+// snapshots share the same body once decrypted.
+const SNAPSHOT_MAGIC: &[u8; 4] = b"ZPS1";
+const SNAPSHOT_VERSION: u8 = 1;
+const SNAPSHOT_NONCE_LEN: usize = 12;
+const SNAPSHOT_SALT_LEN: usize = 16;
+
+// Argon2 (not a single unsalted SHA256 round) so a leaked snapshot can't have
+// its passphrase brute-forced offline at hashing speed. The salt is random
+// per snapshot and stored alongside the ciphertext, since it isn't secret.
+fn derive_key(passphrase: &str, salt: &[u8; SNAPSHOT_SALT_LEN]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .expect("32 bytes is within argon2's output length limits");
+    key
+}
+
+// ChaCha20-Poly1305 (AEAD), not bare ChaCha20: a bare stream cipher is
+// malleable, so a tampered snapshot would silently decrypt to corrupted
+// plaintext instead of failing. The Poly1305 tag makes tampering detectable.
+fn encrypt_payload(payload: &[u8], passphrase: &str) -> Vec<u8> {
+    let mut salt = [0u8; SNAPSHOT_SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let mut nonce_bytes = [0u8; SNAPSHOT_NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce_bytes);
+
+    let key = derive_key(passphrase, &salt);
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce_bytes), payload)
+        .expect("encrypting with a freshly generated nonce cannot fail");
+
+    let mut out = Vec::with_capacity(SNAPSHOT_SALT_LEN + SNAPSHOT_NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce_bytes);
+    out.extend_from_slice(&ciphertext);
+    out
+}
+
+fn decrypt_payload(data: &[u8], passphrase: &str) -> io::Result<Vec<u8>> {
+    if data.len() < SNAPSHOT_SALT_LEN + SNAPSHOT_NONCE_LEN {
+        return Err(Error::new(ErrorKind::InvalidData, "snapshot too short to contain a salt and nonce"));
+    }
+    let (salt_bytes, rest) = data.split_at(SNAPSHOT_SALT_LEN);
+    let (nonce_bytes, ciphertext) = rest.split_at(SNAPSHOT_NONCE_LEN);
+
+    let mut salt = [0u8; SNAPSHOT_SALT_LEN];
+    salt.copy_from_slice(salt_bytes);
+    let key = derive_key(passphrase, &salt);
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    cipher.decrypt(Nonce::from_slice(nonce_bytes), ciphertext).map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidData,
+            "snapshot failed authentication (wrong passphrase or corrupted/tampered data)",
+        )
+    })
+}
+
+fn write_len_prefixed(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn read_len_prefixed<'a>(bytes: &'a [u8], cursor: &mut usize) -> io::Result<&'a [u8]> {
+    let len_bytes = bytes
+        .get(*cursor..*cursor + 4)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "truncated snapshot (length prefix)"))?;
+    let len = u32::from_le_bytes(len_bytes.try_into().unwrap()) as usize;
+    *cursor += 4;
+
+    let value = bytes
+        .get(*cursor..*cursor + len)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "truncated snapshot (value)"))?;
+    *cursor += len;
+
+    Ok(value)
+}
+
+impl Storage {
+    fn encode_snapshot(&self) -> Vec<u8> {
+        let entries = self.backend.iter();
+
+        let mut out = Vec::new();
+        out.extend_from_slice(SNAPSHOT_MAGIC);
+        out.push(SNAPSHOT_VERSION);
+        write_len_prefixed(&mut out, self.name.as_bytes());
+        out.push(self.type_.as_u8());
+        out.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+
+        for (key, data) in &entries {
+            write_len_prefixed(&mut out, key.as_bytes());
+            write_len_prefixed(&mut out, data.as_bytes());
+        }
+
+        out
+    }
+
+    fn decode_snapshot(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < SNAPSHOT_MAGIC.len() + 1 || &bytes[..SNAPSHOT_MAGIC.len()] != SNAPSHOT_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a zippo storage snapshot"));
+        }
+        if bytes[SNAPSHOT_MAGIC.len()] != SNAPSHOT_VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, "unsupported snapshot version"));
+        }
+
+        let mut cursor = SNAPSHOT_MAGIC.len() + 1;
+        let name = String::from_utf8_lossy(read_len_prefixed(bytes, &mut cursor)?).into_owned();
+
+        let type_byte = *bytes
+            .get(cursor)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "truncated snapshot (type)"))?;
+        cursor += 1;
+        let type_ = StorageType::from_u8(type_byte)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "unknown storage type in snapshot"))?;
+
+        let count_bytes = bytes
+            .get(cursor..cursor + 4)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "truncated snapshot (count)"))?;
+        let count = u32::from_le_bytes(count_bytes.try_into().unwrap());
+        cursor += 4;
+
+        let storage = Self::with_backend(&name, type_, Box::new(IndexMapBackend::new()));
+        for _ in 0..count {
+            let key = String::from_utf8_lossy(read_len_prefixed(bytes, &mut cursor)?).into_owned();
+            let data = String::from_utf8_lossy(read_len_prefixed(bytes, &mut cursor)?).into_owned();
+            storage.backend.insert(key, data);
+        }
+
+        Ok(storage)
+    }
+
+    /// Serializes this store to `path`, optionally encrypting it with
+    /// ChaCha20-Poly1305 under a key derived from `passphrase` (Argon2, with
+    /// a random salt) so sensitive memory can be persisted at rest.
+    pub fn save_to(&self, path: impl AsRef<Path>, passphrase: Option<&str>) -> io::Result<()> {
+        let payload = self.encode_snapshot();
+        let bytes = match passphrase {
+            Some(pass) => encrypt_payload(&payload, pass),
+            None => payload,
+        };
+        fs::write(path, bytes)
+    }
+
+    /// Rebuilds a `Storage` from a snapshot written by [`Storage::save_to`],
+    /// preserving insertion order. `passphrase` must match the one used to
+    /// save it, if any.
+    pub fn load_from(path: impl AsRef<Path>, passphrase: Option<&str>) -> io::Result<Self> {
+        let raw = fs::read(path)?;
+        let payload = match passphrase {
+            Some(pass) => decrypt_payload(&raw, pass)?,
+            None => raw,
+        };
+        Self::decode_snapshot(&payload)
+    }
+}
+
+/// Owns a set of named stores and checkpoints them together, so a
+/// long-running agent can restore its full state (tagged/untagged/current
+/// stores alike) rather than one store at a time.
+pub struct StorageRegistry {
+    stores: Vec<Arc<Storage>>,
+    passphrase: Option<String>,
+}
+
+impl StorageRegistry {
+    pub fn new(passphrase: Option<String>) -> Self {
+        Self {
+            stores: Vec::new(),
+            passphrase,
+        }
+    }
+
+    pub fn register(&mut self, storage: Arc<Storage>) {
+        self.stores.push(storage);
+    }
+
+    fn snapshot_path(dir: &Path, name: &str) -> std::path::PathBuf {
+        dir.join(format!("{name}.snapshot"))
+    }
+
+    /// Writes every registered store to `dir`, one file per store name.
+    pub fn checkpoint(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        for storage in &self.stores {
+            let path = Self::snapshot_path(dir, &storage.name);
+            storage.save_to(path, self.passphrase.as_deref())?;
+        }
+        Ok(())
+    }
+
+    /// Spawns a background thread that calls [`StorageRegistry::checkpoint`]
+    /// every `interval`, so a long-running agent can resume exactly where it
+    /// left off after a crash or restart without remembering to checkpoint
+    /// manually. A failed checkpoint is logged rather than propagated, since
+    /// there's no caller left to hand the error to once the loop is running;
+    /// the next tick tries again.
+    pub fn spawn_periodic_checkpoint(
+        self: Arc<Self>,
+        dir: impl Into<std::path::PathBuf>,
+        interval: Duration,
+    ) -> std::thread::JoinHandle<()> {
+        let dir = dir.into();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            if let Err(err) = self.checkpoint(&dir) {
+                error!(error = %err, dir = %dir.display(), "periodic checkpoint failed");
+            }
+        })
+    }
+
+    /// Restores every registered store from `dir`, leaving stores with no
+    /// matching snapshot file untouched.
+    pub fn restore(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        for storage in &self.stores {
+            let path = Self::snapshot_path(dir, &storage.name);
+            if !path.exists() {
+                continue;
+            }
+            let restored = Storage::load_from(path, self.passphrase.as_deref())?;
+            storage.backend.clear();
+            for (key, data) in restored.backend.iter() {
+                storage.backend.insert(key, data);
+            }
+        }
+        Ok(())
     }
 }